@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::spritesheet::SpriteSheet;
+
+/// Index of everything the last successful build cached, keyed by the
+/// cache-relative base path of the sheet/font it belongs to.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+	pub spritesheets: BTreeMap<String, CachedPages>,
+	pub fonts: BTreeMap<String, CachedPages>,
+}
+
+/// How many pages each resolution of a cached sheet was split into, and
+/// whether it was built with a JSON manifest, so it can be reconstructed
+/// into a `SheetBundles` without re-running the packer.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct CachedPages {
+	pub sd: usize,
+	pub hd: usize,
+	pub uhd: usize,
+	pub manifest: bool,
+}
+
+impl Cache {
+	pub fn fetch_spritesheet_bundles(&self, sheet: &SpriteSheet) -> Option<(PathBuf, CachedPages)> {
+		self.spritesheets
+			.get(&sheet.name)
+			.map(|pages| (PathBuf::from(&sheet.name), *pages))
+	}
+}
+
+pub struct CacheBundle {
+	pub cache: Cache,
+	pub dir: PathBuf,
+}
+
+impl CacheBundle {
+	/// Extracts a single cached file into `dest`, returning an error instead
+	/// of panicking if the cached file is missing or corrupt so callers can
+	/// fall back to rebuilding from scratch.
+	pub fn try_extract_cached_into(&self, cache_path: &str, dest: &Path) -> std::io::Result<()> {
+		fs::copy(self.dir.join(cache_path), dest)?;
+		Ok(())
+	}
+}
@@ -9,7 +9,7 @@ use std::io::BufRead;
 /**
  * geode info
  */
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Subcommand, Debug)]
 pub enum Info {
@@ -36,11 +36,103 @@ pub enum Info {
 	List,
 
 	/// Setup config (if you have manually installed Geode)
-	Setup {},
+	Setup {
+		/// Skip verifying that the chosen folder contains a supported
+		/// Geometry Dash install (useful for unrecognized custom builds)
+		#[clap(long)]
+		skip_verify: bool,
+	},
 }
 
 const CONFIGURABLES: [&str; 3] = ["default-developer", "sdk-path", "sdk-nightly"];
 
+#[cfg(target_os = "windows")]
+fn gd_executable(gd_dir: &Path) -> PathBuf {
+	gd_dir.join("GeometryDash.exe")
+}
+
+#[cfg(target_os = "macos")]
+fn gd_executable(gd_dir: &Path) -> PathBuf {
+	gd_dir.join("Geometry Dash.app/Contents/MacOS/Geometry Dash")
+}
+
+#[cfg(target_os = "linux")]
+fn gd_executable(gd_dir: &Path) -> PathBuf {
+	gd_dir.join("GeometryDash.exe")
+}
+
+/// Real GD executables are tens of megabytes; anything smaller is certainly
+/// not a real install and lets us reject obvious false positives before
+/// even scanning the file for a version marker.
+const MIN_GD_EXECUTABLE_SIZE: u64 = 20 * 1024 * 1024;
+
+/// Geometry Dash's own internal class name, used as an anchor: the version
+/// marker is expected to show up shortly before it, which a coincidental
+/// `"2.xxx"`-shaped string from an unrelated linked library would not do.
+const VERSION_ANCHOR: &[u8] = b"GameManager";
+const ANCHOR_SEARCH_WINDOW: usize = 256;
+
+/// Geometry Dash embeds its own version as a plain `"2.xxx"` ASCII string
+/// somewhere in its executable, close to the `GameManager` class name.
+/// Requiring both to appear together tells a real GD binary apart from an
+/// arbitrary file without having to keep a table of per-release hashes in
+/// sync with every new GD update.
+fn detect_gd_version(exe: &Path) -> Option<String> {
+	let bytes = std::fs::read(exe).ok()?;
+
+	bytes
+		.split(|&b| b == 0)
+		.filter_map(|chunk| {
+			let offset = chunk.as_ptr() as usize - bytes.as_ptr() as usize;
+			std::str::from_utf8(chunk).ok().map(|s| (offset + chunk.len(), s))
+		})
+		.find_map(|(chunk_end, s)| {
+			let s = s.trim();
+			let (major, minor) = s.split_once('.')?;
+			if !(major == "2" && minor.len() == 3 && minor.bytes().all(|b| b.is_ascii_digit())) {
+				return None;
+			}
+
+			let window_end = (chunk_end + ANCHOR_SEARCH_WINDOW).min(bytes.len());
+			bytes[chunk_end..window_end]
+				.windows(VERSION_ANCHOR.len())
+				.any(|w| w == VERSION_ANCHOR)
+				.then(|| s.to_string())
+		})
+}
+
+/// Verifies that `gd_dir` contains a supported Geometry Dash install by
+/// locating the platform's main executable, checking its size is at least
+/// plausible for a real install, and reading its version marker. Returns
+/// the detected version on success.
+fn verify_gd_install(gd_dir: &Path) -> Result<String, String> {
+	let exe = gd_executable(gd_dir);
+	if !exe.is_file() {
+		return Err(format!(
+			"Could not find the Geometry Dash executable at '{}'",
+			exe.display()
+		));
+	}
+
+	let size = exe
+		.metadata()
+		.map_err(|e| format!("Unable to read metadata for '{}': {}", exe.display(), e))?
+		.len();
+	if size < MIN_GD_EXECUTABLE_SIZE {
+		return Err(format!(
+			"'{}' is too small ({} bytes) to be a real Geometry Dash executable",
+			exe.display(),
+			size
+		));
+	}
+
+	detect_gd_version(&exe).ok_or_else(|| {
+		"Could not find a Geometry Dash version marker in the executable, \
+		this doesn't look like a supported Geometry Dash install"
+			.to_string()
+	})
+}
+
 fn get_bool(value: &str) -> Option<bool> {
 	let lower = value.to_ascii_lowercase();
 
@@ -108,11 +200,11 @@ pub fn subcommand(config: &mut Config, cmd: Info) {
 			}
 		}
 
-		Info::Setup {} => {
+		Info::Setup { skip_verify } => {
 			if config.profiles.is_empty() {
 				info!("Please enter the path to the Geometry Dash folder:");
 
-				let path = loop {
+				let (path, version) = loop {
 					let mut buf = String::new();
 					match std::io::stdin().lock().read_line(&mut buf) {
 						Ok(_) => {}
@@ -139,9 +231,18 @@ pub fn subcommand(config: &mut Config, cmd: Info) {
 						fail!("Given path appears to be empty");
 						continue;
 					}
-					// todo: maybe do some checksum verification
-					// to make sure GD 2.113 is in the folder
-					break path;
+
+					if skip_verify {
+						break (path, None);
+					}
+
+					match verify_gd_install(&path) {
+						Ok(version) => break (path, Some(version)),
+						Err(e) => {
+							fail!("{} (use --skip-verify to use this folder anyway)", e);
+							continue;
+						}
+					}
 				};
 
 				info!("Please enter a name for the profile:");
@@ -153,9 +254,11 @@ pub fn subcommand(config: &mut Config, cmd: Info) {
 					};
 				};
 
-				config
-					.profiles
-					.push(RefCell::new(Profile::new(name.trim().into(), path)));
+				config.profiles.push(RefCell::new(Profile::new(
+					name.trim().into(),
+					path,
+					version,
+				)));
 				config.current_profile = Some(name.trim().into());
 				done!("Profile added");
 			}
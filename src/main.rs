@@ -9,6 +9,7 @@ use clap::{Parser, Subcommand};
  */
 use std::path::PathBuf;
 
+mod cache;
 mod info;
 mod package;
 mod profile;
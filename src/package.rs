@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+/**
+ * geode package: Subcommand for managing .geode files
+ */
+use crate::config::Config;
+use crate::util::mod_file::ModFileInfo;
+use crate::util::spritesheet::{
+	get_spritesheet_bundles_with_max_size, SpriteSheet, DEFAULT_MAX_PAGE_SIZE,
+};
+use crate::NiceUnwrap;
+use crate::{done, fail, info};
+
+#[derive(Subcommand, Debug)]
+pub enum Package {
+	/// Pack a folder of sprite source files into a Geode spritesheet
+	Resources {
+		/// Folder containing the sprite source files
+		path: PathBuf,
+
+		/// Mod id the packed sprites belong to, e.g. `geode.loader`
+		#[clap(long)]
+		id: String,
+
+		/// Name to give the resulting spritesheet
+		#[clap(long, default_value = "spritesheet")]
+		name: String,
+
+		/// Emit a JSON atlas manifest for each page (`name-0.json`,
+		/// `name-0-hd.json`, ...) alongside its png; each manifest covers only
+		/// the frames of its own page
+		#[clap(long)]
+		manifest: bool,
+
+		/// Maximum width/height of a single spritesheet page, in pixels
+		#[clap(long, default_value_t = DEFAULT_MAX_PAGE_SIZE)]
+		max_page_size: u32,
+	},
+
+	/// Install a built .geode package to the current profile
+	Install {
+		/// Location of the .geode package to install
+		path: PathBuf,
+	},
+}
+
+fn build_resources(path: &Path, id: &str, name: &str, manifest: bool, max_page_size: u32) {
+	let files = std::fs::read_dir(path)
+		.nice_unwrap(format!("Unable to read resource folder '{}'", path.display()))
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.filter(|entry| entry.is_file())
+		.collect();
+
+	let sheet = SpriteSheet { name: name.to_string(), files };
+	let mod_info = ModFileInfo { id: id.to_string() };
+
+	get_spritesheet_bundles_with_max_size(
+		&sheet,
+		path,
+		&mut None,
+		&mod_info,
+		false,
+		manifest,
+		max_page_size,
+		max_page_size,
+	);
+
+	done!("Packed resources '{}'", name);
+}
+
+pub fn install(config: &mut Config, path: &Path) {
+	if config.current_profile.is_none() {
+		fail!("No active profile to install into; run `geode config setup` first");
+		return;
+	}
+
+	// todo: unpack the .geode archive into the active profile's mods folder
+	info!("Installing '{}'", path.display());
+	done!("Installed {}", path.display());
+}
+
+pub fn subcommand(config: &mut Config, cmd: Package) {
+	match cmd {
+		Package::Resources { path, id, name, manifest, max_page_size } => {
+			build_resources(&path, &id, &name, manifest, max_page_size)
+		}
+
+		Package::Install { path } => install(config, &path),
+	}
+}
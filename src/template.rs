@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tera::{Context, Tera};
+/**
+ * geode new: Create new geode project from template
+ */
+use crate::config::Config;
+use crate::NiceUnwrap;
+use crate::{done, info};
+
+const AVAILABLE_FEATURES: [&str; 3] = ["gui", "settings", "keybinds"];
+
+/// Template files bundled with the CLI, keyed by their path relative to the
+/// generated project root. Each may contain `{{ variable }}` placeholders
+/// and `{% if %}` blocks, evaluated against the answers collected in
+/// `collect_answers`.
+const TEMPLATE_FILES: &[(&str, &str)] = &[
+	("mod.json", include_str!("../templates/mod.json.tera")),
+	("src/main.cpp", include_str!("../templates/main.cpp.tera")),
+	("CMakeLists.txt", include_str!("../templates/CMakeLists.txt.tera")),
+];
+
+/// Answers collected for a single `geode new` run, forming the Tera context
+/// the template tree is rendered against.
+#[derive(Serialize)]
+struct TemplateAnswers {
+	mod_name: String,
+	developer: String,
+	/// Slugified form of `mod_name`, safe to use in the dotted mod id
+	mod_id: String,
+	/// Slugified form of `developer`, safe to use in the dotted mod id
+	developer_id: String,
+	strip_comments: bool,
+	features: Vec<String>,
+	example_hooks: bool,
+}
+
+/// Reduces `value` to the `[a-z0-9_-]` charset dotted mod ids are built
+/// from (e.g. `geode.loader`), so free text like `"My Cool Mod"` becomes
+/// `"my-cool-mod"` instead of an id containing spaces.
+fn slugify(value: &str) -> String {
+	value
+		.trim()
+		.to_ascii_lowercase()
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '-' })
+		.collect::<String>()
+		.split('-')
+		.filter(|part| !part.is_empty())
+		.collect::<Vec<_>>()
+		.join("-")
+}
+
+fn prompt(question: &str, default: Option<&str>) -> String {
+	match default {
+		Some(default) if !default.is_empty() => info!("{} [{}]", question, default),
+		_ => info!("{}", question),
+	}
+
+	let mut buf = String::new();
+	std::io::stdin()
+		.read_line(&mut buf)
+		.nice_unwrap("Unable to read input");
+
+	let answer = buf.trim();
+	if answer.is_empty() {
+		default.unwrap_or("").to_string()
+	} else {
+		answer.to_string()
+	}
+}
+
+/// Collects the answers needed to render the template. `name` and `strip`
+/// map onto `mod_name`/`strip_comments` so `geode new --name foo --strip`
+/// keeps working non-interactively, falling back to prompts (and
+/// `Config::default_developer` for the developer id) for anything else.
+fn collect_answers(config: &Config, name: Option<String>, strip: bool) -> TemplateAnswers {
+	let mod_name = name.unwrap_or_else(|| prompt("Mod name:", None));
+
+	// Use the configured default developer without blocking on stdin, so
+	// `geode new --name foo --strip` can run fully non-interactively
+	let developer = match config.default_developer.as_deref() {
+		Some(default) => {
+			info!("Using developer '{}' from config", default);
+			default.to_string()
+		}
+		None => prompt("Developer username:", None),
+	};
+
+	let features = if strip {
+		Vec::new()
+	} else {
+		prompt(
+			&format!(
+				"SDK features to enable ({}), comma separated, empty for none:",
+				AVAILABLE_FEATURES.join(", ")
+			),
+			Some(""),
+		)
+		.split(',')
+		.map(|s| s.trim().to_string())
+		.filter(|s| !s.is_empty() && AVAILABLE_FEATURES.contains(&s.as_str()))
+		.collect()
+	};
+
+	let example_hooks = !strip && prompt("Include an example hook? (y/n)", Some("n")) == "y";
+
+	let mod_id = slugify(&mod_name);
+	let developer_id = slugify(&developer);
+
+	TemplateAnswers {
+		mod_name,
+		developer,
+		mod_id,
+		developer_id,
+		strip_comments: strip,
+		features,
+		example_hooks,
+	}
+}
+
+fn render_into(answers: &TemplateAnswers, path: &PathBuf) {
+	let mut tera = Tera::default();
+	tera.add_raw_templates(TEMPLATE_FILES.iter().copied())
+		.nice_unwrap("Unable to load mod template");
+
+	let mut context = Context::new();
+	context.insert("mod_name", &answers.mod_name);
+	context.insert("developer", &answers.developer);
+	context.insert("mod_id", &answers.mod_id);
+	context.insert("developer_id", &answers.developer_id);
+	context.insert("strip_comments", &answers.strip_comments);
+	context.insert("features", &answers.features);
+	context.insert("example_hooks", &answers.example_hooks);
+
+	for (name, _) in TEMPLATE_FILES {
+		let rendered = tera
+			.render(name, &context)
+			.nice_unwrap(format!("Unable to render template file '{}'", name));
+
+		let out_path = path.join(name);
+		std::fs::create_dir_all(out_path.parent().unwrap())
+			.nice_unwrap("Unable to create mod directory");
+		std::fs::write(&out_path, rendered)
+			.nice_unwrap(format!("Unable to write '{}'", out_path.display()));
+	}
+}
+
+pub fn build_template(config: &mut Config, name: Option<String>, path: Option<PathBuf>, strip: bool) {
+	let path = path.unwrap_or_else(|| PathBuf::from("."));
+
+	let answers = collect_answers(config, name, strip);
+
+	render_into(&answers, &path);
+
+	done!("Created mod project '{}'", answers.mod_name);
+}
@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+
+/// A single configured Geometry Dash installation.
+pub struct Profile {
+	pub name: String,
+	pub path: PathBuf,
+	/// Geometry Dash version detected by `verify_gd_install` when the profile
+	/// was set up, or `None` if verification was skipped with `--skip-verify`.
+	pub version: Option<String>,
+}
+
+impl Profile {
+	pub fn new(name: String, path: PathBuf, version: Option<String>) -> Profile {
+		Profile { name, path, version }
+	}
+}
@@ -4,15 +4,20 @@ use std::path::{Path, PathBuf};
 use image::{imageops, ImageFormat, RgbaImage};
 use serde_json::json;
 use texture_packer::exporter::ImageExporter;
-use texture_packer::{TexturePacker, TexturePackerConfig};
+use texture_packer::{MultiTexturePacker, TexturePackerConfig};
 
-use crate::cache::CacheBundle;
+use crate::cache::{CacheBundle, CachedPages};
 use crate::rgba4444::RGBA4444;
 use crate::NiceUnwrap;
-use crate::{done, info};
+use crate::{done, info, warn};
 
 use super::mod_file::ModFileInfo;
 
+/// Cap on a single page's width/height. Many GD targets enforce a 4096px (or
+/// 8192px) GPU texture limit, so sheets that don't fit on one page spill onto
+/// additional pages instead of producing an oversized texture.
+pub const DEFAULT_MAX_PAGE_SIZE: u32 = 4096;
+
 pub struct Sprite {
 	pub name: String,
 	pub image: RgbaImage,
@@ -21,6 +26,9 @@ pub struct Sprite {
 pub struct SheetBundle {
 	pub png: PathBuf,
 	pub plist: PathBuf,
+	/// Path to this page's `serde`-friendly atlas manifest, present only
+	/// when the sheet was built with `--manifest`.
+	pub manifest: Option<PathBuf>,
 }
 
 pub struct SpriteSheet {
@@ -29,39 +37,59 @@ pub struct SpriteSheet {
 }
 
 pub struct SheetBundles {
-	pub sd: SheetBundle,
-	pub hd: SheetBundle,
-	pub uhd: SheetBundle,
+	pub sd: Vec<SheetBundle>,
+	pub hd: Vec<SheetBundle>,
+	pub uhd: Vec<SheetBundle>,
 }
 
 impl SheetBundles {
-	fn new_file(base: PathBuf) -> SheetBundle {
-		let mut plist = base.to_owned();
-		plist.set_extension("plist");
+	fn page_png(base: &Path, suffix: &str, page: usize) -> PathBuf {
+		let base_name = base.file_stem().unwrap().to_str().unwrap();
+		base.with_file_name(format!("{base_name}-{page}{suffix}.png"))
+	}
 
-		SheetBundle { png: base, plist }
+	fn shared_plist(base: &Path, suffix: &str) -> PathBuf {
+		let base_name = base.file_stem().unwrap().to_str().unwrap();
+		base.with_file_name(format!("{base_name}{suffix}.plist"))
 	}
 
-	pub fn new(mut base: PathBuf) -> SheetBundles {
-		base.set_extension("png");
+	fn page_manifest(base: &Path, suffix: &str, page: usize) -> PathBuf {
+		let base_name = base.file_stem().unwrap().to_str().unwrap();
+		base.with_file_name(format!("{base_name}-{page}{suffix}.json"))
+	}
 
-		let base_name = base.file_stem().unwrap().to_str().unwrap().to_string();
+	/// Builds the bundle paths for one resolution's pages. The plist is
+	/// shared across every page of a resolution since frame metadata for all
+	/// pages is merged into a single file; the JSON manifest, if enabled,
+	/// covers one page each.
+	fn new_pages(base: &Path, suffix: &str, pages: usize, manifest: bool) -> Vec<SheetBundle> {
+		let plist = SheetBundles::shared_plist(base, suffix);
+
+		(0..pages.max(1))
+			.map(|page| SheetBundle {
+				png: SheetBundles::page_png(base, suffix, page),
+				plist: plist.clone(),
+				manifest: manifest.then(|| SheetBundles::page_manifest(base, suffix, page)),
+			})
+			.collect()
+	}
 
-		let hd = base.with_file_name(base_name.to_string() + "-hd.png");
-		let uhd = base.with_file_name(base_name + "-uhd.png");
+	pub fn new(mut base: PathBuf, pages: CachedPages) -> SheetBundles {
+		base.set_extension("png");
 
 		SheetBundles {
-			sd: SheetBundles::new_file(base),
-			hd: SheetBundles::new_file(hd),
-			uhd: SheetBundles::new_file(uhd),
+			sd: SheetBundles::new_pages(&base, "", pages.sd, pages.manifest),
+			hd: SheetBundles::new_pages(&base, "-hd", pages.hd, pages.manifest),
+			uhd: SheetBundles::new_pages(&base, "-uhd", pages.uhd, pages.manifest),
 		}
 	}
 
 	pub fn cache_name(&self, working_dir: &Path) -> PathBuf {
-		if self.sd.png.is_relative() {
-			self.sd.png.to_path_buf()
+		let first = &self.sd[0].png;
+		if first.is_relative() {
+			first.to_path_buf()
 		} else {
-			self.sd.png.strip_prefix(working_dir).unwrap().to_path_buf()
+			first.strip_prefix(working_dir).unwrap().to_path_buf()
 		}
 	}
 }
@@ -74,6 +102,50 @@ pub fn read_to_image(path: &Path) -> RgbaImage {
 		.to_rgba8()
 }
 
+/// Converts a single source file into one or more sprites. Regular image
+/// files (anything the `image` crate decodes) become a single sprite named
+/// after the file stem. Aseprite files are exploded into one sprite per
+/// frame, each composited from its visible layers onto the canvas so cel
+/// positions line up, and named after the frame's tag (or a zero-padded
+/// frame index for frames that are not inside any tag).
+fn file_to_sprites(path: &Path) -> Vec<Sprite> {
+	let is_aseprite = path
+		.extension()
+		.and_then(|ext| ext.to_str())
+		.map(|ext| ext.eq_ignore_ascii_case("ase") || ext.eq_ignore_ascii_case("aseprite"))
+		.unwrap_or(false);
+
+	if !is_aseprite {
+		return vec![Sprite {
+			name: path.file_stem().unwrap().to_str().unwrap().to_string(),
+			image: read_to_image(path),
+		}];
+	}
+
+	let stem = path.file_stem().unwrap().to_str().unwrap();
+
+	let ase = asefile::AsepriteFile::read_file(path)
+		.nice_unwrap(format!("Error reading aseprite file '{}'", path.display()));
+
+	let tags: Vec<_> = ase.tags().collect();
+
+	(0..ase.num_frames())
+		.map(|frame| {
+			let image = ase.frame(frame).image();
+
+			let name = match tags
+				.iter()
+				.find(|tag| (tag.from_frame()..=tag.to_frame()).contains(&frame))
+			{
+				Some(tag) => format!("{stem}_{}_{}", tag.name(), frame - tag.from_frame()),
+				None => format!("{stem}_{frame:04}"),
+			};
+
+			Sprite { name, image }
+		})
+		.collect()
+}
+
 pub fn downscale(img: &mut RgbaImage, factor: u32) {
 	*img = imageops::resize(
 		img,
@@ -87,55 +159,49 @@ pub fn downscale(img: &mut RgbaImage, factor: u32) {
 }
 
 fn initialize_spritesheet_bundle(
-	bundle: &SheetBundle,
+	base: &Path,
+	suffix: &str,
 	sheet: &SpriteSheet,
 	factor: u32,
 	mod_info: &ModFileInfo,
-) {
-	// Convert all files to sprites
-	let mut sprites: Vec<Sprite> = sheet
-		.files
-		.iter()
-		.map(|x| Sprite {
-			name: x.file_stem().unwrap().to_str().unwrap().to_string(),
-			image: read_to_image(x),
-		})
-		.collect();
+	max_width: u32,
+	max_height: u32,
+	manifest: bool,
+) -> Vec<SheetBundle> {
+	// Convert all files to sprites, expanding aseprite files into one sprite
+	// per frame
+	let mut sprites: Vec<Sprite> = sheet.files.iter().flat_map(|x| file_to_sprites(x)).collect();
 
 	// Resize
 	for sprite in &mut sprites {
 		downscale(&mut sprite.image, factor);
 	}
 
-	// Determine maximum dimensions of sprite sheet
-	let largest_width: u32 = sprites.iter().map(|x| x.image.width()).max().unwrap();
-
-	let mean_height =
-		sprites.iter().map(|x| x.image.height() as f64).sum::<f64>() / sprites.len() as f64;
-	let width_sum = sprites.iter().map(|x| x.image.width()).sum::<u32>() as f64;
-
-	let mut max_width = (width_sum * mean_height).sqrt() as u32;
-
-	if max_width < largest_width {
-		max_width = largest_width + 2;
-	}
-
-	// Setup texture packer
+	// Setup texture packer, spilling onto additional pages if everything
+	// doesn't fit within max_width/max_height
 	let config = TexturePackerConfig {
 		max_width,
-		max_height: u32::MAX,
+		max_height,
 		allow_rotation: false,
 		texture_outlines: false,
 		border_padding: 0,
 		..Default::default()
 	};
-	let mut texture_packer = TexturePacker::new_skyline(config);
+	let mut texture_packer = MultiTexturePacker::new_skyline(config);
 
 	// Pack textures
 	info!("Packing sprites");
-	sprites
-		.iter()
-		.for_each(|x| texture_packer.pack_ref(&x.name, &x.image).unwrap());
+	for sprite in sprites {
+		let name = sprite.name.clone();
+		let (width, height) = (sprite.image.width(), sprite.image.height());
+
+		texture_packer
+			.pack_own(sprite.name, sprite.image)
+			.nice_unwrap(format!(
+				"Sprite '{name}' ({width}x{height}) is too large to fit on a \
+				{max_width}x{max_height} page; raise --max-page-size or shrink the sprite"
+			));
+	}
 	done!("Packed sprites");
 
 	let sprite_name_in_sheet = |name: &String| {
@@ -148,20 +214,74 @@ fn initialize_spritesheet_bundle(
 			+ ".png"
 	};
 
-	// Initialize the plist file
-	let frame_info = texture_packer.get_frames().iter().map(|(name, frame)| {
-		(sprite_name_in_sheet(name), json!({
-			"textureRotated": frame.rotated,
-			"spriteSourceSize": format!("{{{}, {}}}", frame.source.w, frame.source.h),
-			"spriteSize": format!("{{{}, {}}}", frame.frame.w, frame.frame.h),
-			"textureRect": format!("{{{{{}, {}}}, {{{}, {}}}}}", frame.frame.x, frame.frame.y, frame.frame.w, frame.frame.h),
-			"spriteOffset": format!("{{{}, {}}}", frame.source.x, -(frame.source.y as i32)),
-		}))
-	}).collect::<BTreeMap<_, _>>();
-	// Using BTreeMap to make sure all packings for the same input produce
-	// identical output via sorted keys
-
-	// Write plist
+	let pages = texture_packer.get_pages();
+	let bundles = SheetBundles::new_pages(base, suffix, pages.len(), manifest);
+
+	// Merge the frame dictionaries from every page into a single plist,
+	// tagging each frame with the page it was packed onto so the loader
+	// knows which PNG to read it from. Using a BTreeMap to make sure all
+	// packings for the same input produce identical output via sorted keys
+	let mut frame_info = BTreeMap::new();
+
+	info!("Exporting {} page(s)", pages.len());
+	for (page, bundle) in pages.iter().zip(&bundles) {
+		let page_file_name = bundle.png.file_name().unwrap().to_str().unwrap().to_string();
+
+		let mut manifest_frames = BTreeMap::new();
+		for (name, frame) in page.get_frames() {
+			let sprite_name = sprite_name_in_sheet(name);
+
+			frame_info.insert(sprite_name.clone(), json!({
+				"textureRotated": frame.rotated,
+				"spriteSourceSize": format!("{{{}, {}}}", frame.source.w, frame.source.h),
+				"spriteSize": format!("{{{}, {}}}", frame.frame.w, frame.frame.h),
+				"textureRect": format!("{{{{{}, {}}}, {{{}, {}}}}}", frame.frame.x, frame.frame.y, frame.frame.w, frame.frame.h),
+				"spriteOffset": format!("{{{}, {}}}", frame.source.x, -(frame.source.y as i32)),
+				"textureFileName": page_file_name,
+			}));
+
+			if bundle.manifest.is_some() {
+				manifest_frames.insert(sprite_name, json!({
+					"x": frame.frame.x,
+					"y": frame.frame.y,
+					"w": frame.frame.w,
+					"h": frame.frame.h,
+					"rotated": frame.rotated,
+					"source_w": frame.source.w,
+					"source_h": frame.source.h,
+					"offset_x": frame.source.x,
+					"offset_y": frame.source.y,
+				}));
+			}
+		}
+
+		// Write png
+		let mut file = std::fs::File::create(&bundle.png).unwrap();
+		let exporter = ImageExporter::export(page).unwrap();
+		exporter
+			.write_to(&mut file, ImageFormat::Png)
+			.nice_unwrap("Unable to write to png file");
+
+		// Write the JSON atlas manifest for this page, if requested
+		if let Some(manifest_path) = &bundle.manifest {
+			let manifest_json = json!({
+				"image": page_file_name,
+				"size": { "w": page.width(), "h": page.height() },
+				"frames": manifest_frames,
+			});
+
+			std::fs::write(
+				manifest_path,
+				serde_json::to_string_pretty(&manifest_json).unwrap(),
+			)
+			.nice_unwrap(format!(
+				"Unable to write to manifest file '{}'",
+				manifest_path.display()
+			));
+		}
+	}
+
+	// Write plist, shared by every page of this resolution
 	let plist_file = json!({
 		"frames": frame_info,
 		"metadata": {
@@ -169,45 +289,66 @@ fn initialize_spritesheet_bundle(
 		}
 	});
 
-	plist::to_file_xml(&bundle.plist, &plist_file).nice_unwrap("Unable to write to plist file");
-
-	// Write png
-	let mut file = std::fs::File::create(&bundle.png).unwrap();
-
-	info!("Exporting");
-
-	let exporter = ImageExporter::export(&texture_packer).unwrap();
-	exporter
-		.write_to(&mut file, ImageFormat::Png)
-		.nice_unwrap("Unable to write to png file");
+	plist::to_file_xml(&bundles[0].plist, &plist_file).nice_unwrap("Unable to write to plist file");
 
 	done!(
 		"Successfully packed {}",
-		bundle
-			.png
-			.with_extension("")
+		base.with_extension("")
 			.file_name()
 			.unwrap()
 			.to_str()
 			.unwrap()
 			.bright_yellow()
 	);
+
+	bundles
 }
 
+/// Extracts a single cached file into `working_dir`, returning the
+/// destination path on success so a failed extraction can be cleaned up.
 fn extract_from_cache(
 	path: &Path,
 	working_dir: &Path,
-	cache_bundle: &mut CacheBundle,
+	cache_bundle: &CacheBundle,
 	shut_up: bool,
-) {
+) -> Result<PathBuf, std::io::Error> {
 	let path_name = path.to_str().unwrap();
 	if !shut_up {
 		info!("Extracting '{}' from cache", path_name);
 	}
-	cache_bundle.extract_cached_into(
-		path_name,
-		&working_dir.join(path.file_name().unwrap().to_str().unwrap()),
-	);
+	let dest = working_dir.join(path.file_name().unwrap().to_str().unwrap());
+	cache_bundle.try_extract_cached_into(path_name, &dest)?;
+	Ok(dest)
+}
+
+/// Extracts every page/plist of a cached `SheetBundles`. If any file is
+/// missing or corrupt, the partially extracted files are removed and `None`
+/// is returned so the caller can fall back to building from scratch.
+fn try_extract_bundles_from_cache(
+	bundles: &SheetBundles,
+	working_dir: &Path,
+	cache_bundle: &CacheBundle,
+	shut_up: bool,
+) -> Option<()> {
+	let mut extracted = Vec::new();
+
+	for bundle in bundles.sd.iter().chain(&bundles.hd).chain(&bundles.uhd) {
+		let paths = [Some(&bundle.png), Some(&bundle.plist), bundle.manifest.as_ref()];
+		for path in paths.into_iter().flatten() {
+			match extract_from_cache(path, working_dir, cache_bundle, shut_up) {
+				Ok(dest) => extracted.push(dest),
+				Err(e) => {
+					warn!("Cached spritesheet is broken ({}), rebuilding from scratch", e);
+					for dest in &extracted {
+						let _ = std::fs::remove_file(dest);
+					}
+					return None;
+				}
+			}
+		}
+	}
+
+	Some(())
 }
 
 pub fn get_spritesheet_bundles(
@@ -216,6 +357,29 @@ pub fn get_spritesheet_bundles(
 	cache: &mut Option<CacheBundle>,
 	mod_info: &ModFileInfo,
 	shut_up: bool,
+	manifest: bool,
+) -> SheetBundles {
+	get_spritesheet_bundles_with_max_size(
+		sheet,
+		working_dir,
+		cache,
+		mod_info,
+		shut_up,
+		manifest,
+		DEFAULT_MAX_PAGE_SIZE,
+		DEFAULT_MAX_PAGE_SIZE,
+	)
+}
+
+pub fn get_spritesheet_bundles_with_max_size(
+	sheet: &SpriteSheet,
+	working_dir: &Path,
+	cache: &mut Option<CacheBundle>,
+	mod_info: &ModFileInfo,
+	shut_up: bool,
+	manifest: bool,
+	max_width: u32,
+	max_height: u32,
 ) -> SheetBundles {
 	if !shut_up {
 		info!("Fetching spritesheet {}", sheet.name.bright_yellow());
@@ -223,41 +387,35 @@ pub fn get_spritesheet_bundles(
 
 	if let Some(cache_bundle) = cache {
 		// Cache found
-		if let Some(p) = cache_bundle.cache.fetch_spritesheet_bundles(sheet) {
+		if let Some((p, pages)) = cache_bundle.cache.fetch_spritesheet_bundles(sheet) {
 			if !shut_up {
 				info!("Using cached files");
 			}
-			let bundles = SheetBundles::new(p.to_path_buf());
-
-			// Extract all files
-			extract_from_cache(&bundles.sd.png, working_dir, cache_bundle, shut_up);
-			extract_from_cache(&bundles.sd.plist, working_dir, cache_bundle, shut_up);
-			extract_from_cache(&bundles.hd.png, working_dir, cache_bundle, shut_up);
-			extract_from_cache(&bundles.hd.plist, working_dir, cache_bundle, shut_up);
-			extract_from_cache(&bundles.uhd.png, working_dir, cache_bundle, shut_up);
-			extract_from_cache(&bundles.uhd.plist, working_dir, cache_bundle, shut_up);
-
-			done!("Fetched {} from cache", sheet.name.bright_yellow());
-			return bundles;
+			let bundles = SheetBundles::new(p.to_path_buf(), pages);
+
+			if try_extract_bundles_from_cache(&bundles, working_dir, cache_bundle, shut_up).is_some() {
+				done!("Fetched {} from cache", sheet.name.bright_yellow());
+				return bundles;
+			}
 		}
 	}
 
 	if !shut_up {
 		info!("Sheet is not cached, building from scratch");
 	}
-	let bundles = SheetBundles::new(working_dir.join(sheet.name.to_string() + ".png"));
+	let base = working_dir.join(sheet.name.to_string() + ".png");
 
 	// Initialize all files
 
 	info!("Creating normal sheet");
-	initialize_spritesheet_bundle(&bundles.sd, sheet, 4, mod_info);
+	let sd = initialize_spritesheet_bundle(&base, "", sheet, 4, mod_info, max_width, max_height, manifest);
 
 	info!("Creating HD sheet");
-	initialize_spritesheet_bundle(&bundles.hd, sheet, 2, mod_info);
+	let hd = initialize_spritesheet_bundle(&base, "-hd", sheet, 2, mod_info, max_width, max_height, manifest);
 
 	info!("Creating UHD sheet");
-	initialize_spritesheet_bundle(&bundles.uhd, sheet, 1, mod_info);
+	let uhd = initialize_spritesheet_bundle(&base, "-uhd", sheet, 1, mod_info, max_width, max_height, manifest);
 
 	done!("Built spritesheet {}", sheet.name.bright_yellow());
-	bundles
+	SheetBundles { sd, hd, uhd }
 }